@@ -1,8 +1,11 @@
-//! Enumeration of all supported IRCv3 capability extensions.
+//! Enumeration of all supported IRCv3 capability extensions, and negotiation of them via `CAP`.
+use std::str::FromStr;
+
+use client::data::message::Message;
 
 /// List of all supported IRCv3 capability extensions from the
-/// [IRCv3 specifications](http://ircv3.net/irc/). 
-#[derive(Debug, PartialEq)]
+/// [IRCv3 specifications](http://ircv3.net/irc/).
+#[derive(Debug, PartialEq, Clone)]
 pub enum Capability {
     /// [multi-prefix](http://ircv3.net/specs/extensions/multi-prefix-3.1.html)
     MultiPrefix,
@@ -12,6 +15,26 @@ pub enum Capability {
     AwayNotify,
     /// [extended-join](http://ircv3.net/specs/extensions/extended-join-3.1.html)
     ExtendedJoin,
+    /// [server-time](http://ircv3.net/specs/extensions/server-time-3.2.html)
+    ServerTime,
+    /// [message-tags](http://ircv3.net/specs/core/message-tags-3.2.html)
+    MessageTags,
+    /// [batch](http://ircv3.net/specs/extensions/batch-3.2.html)
+    Batch,
+    /// [account-tag](http://ircv3.net/specs/extensions/account-tag-3.2.html)
+    AccountTag,
+    /// [echo-message](http://ircv3.net/specs/extensions/echo-message-3.2.html)
+    EchoMessage,
+    /// [cap-notify](http://ircv3.net/specs/extensions/cap-notify-3.2.html)
+    CapNotify,
+    /// [sasl](http://ircv3.net/specs/extensions/sasl-3.1.html)
+    Sasl,
+    /// [chghost](http://ircv3.net/specs/extensions/chghost-3.2.html)
+    Chghost,
+    /// [invite-notify](http://ircv3.net/specs/extensions/invite-notify-3.2.html)
+    InviteNotify,
+    /// [userhost-in-names](http://ircv3.net/specs/extensions/userhost-in-names-3.2.html)
+    UserhostInNames,
 }
 
 impl AsRef<str> for Capability {
@@ -21,13 +44,125 @@ impl AsRef<str> for Capability {
             Capability::AccountNotify => "account-notify",
             Capability::AwayNotify => "away-notify",
             Capability::ExtendedJoin => "extended-join",
+            Capability::ServerTime => "server-time",
+            Capability::MessageTags => "message-tags",
+            Capability::Batch => "batch",
+            Capability::AccountTag => "account-tag",
+            Capability::EchoMessage => "echo-message",
+            Capability::CapNotify => "cap-notify",
+            Capability::Sasl => "sasl",
+            Capability::Chghost => "chghost",
+            Capability::InviteNotify => "invite-notify",
+            Capability::UserhostInNames => "userhost-in-names",
         }
     }
 }
 
+/// The error returned when parsing a capability token this crate does not recognize.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UnknownCapability(pub String);
+
+impl FromStr for Capability {
+    type Err = UnknownCapability;
+    fn from_str(s: &str) -> Result<Capability, UnknownCapability> {
+        Ok(match s {
+            "multi-prefix" => Capability::MultiPrefix,
+            "account-notify" => Capability::AccountNotify,
+            "away-notify" => Capability::AwayNotify,
+            "extended-join" => Capability::ExtendedJoin,
+            "server-time" => Capability::ServerTime,
+            "message-tags" => Capability::MessageTags,
+            "batch" => Capability::Batch,
+            "account-tag" => Capability::AccountTag,
+            "echo-message" => Capability::EchoMessage,
+            "cap-notify" => Capability::CapNotify,
+            "sasl" => Capability::Sasl,
+            "chghost" => Capability::Chghost,
+            "invite-notify" => Capability::InviteNotify,
+            "userhost-in-names" => Capability::UserhostInNames,
+            _ => return Err(UnknownCapability(s.to_owned())),
+        })
+    }
+}
+
+/// Tracks an in-progress `CAP` negotiation: the capabilities we want, the capabilities the
+/// server has advertised so far (via one or more `CAP * LS` lines), and whether the server
+/// has indicated more `LS` lines are still coming.
+#[derive(Clone, Debug)]
+pub struct NegotiationState {
+    requested: Vec<Capability>,
+    available: Vec<(Capability, Option<String>)>,
+    more_to_come: bool,
+}
+
+impl NegotiationState {
+    /// Starts a negotiation that will request `requested` once the server's full capability
+    /// list has been received.
+    pub fn new(requested: Vec<Capability>) -> NegotiationState {
+        NegotiationState { requested: requested, available: Vec::new(), more_to_come: false }
+    }
+
+    /// Builds the `CAP LS 302` message that kicks off negotiation.
+    pub fn request_ls() -> Message {
+        Message::new(None, "CAP", Some(vec!["LS", "302"]), None)
+    }
+
+    /// Feeds a `CAP * LS [*] :<space-separated tokens>` reply (`*` being the trailing
+    /// continuation marker) into the negotiation, parsing
+    /// each token (optionally of the form `key=value`, e.g. `sasl=PLAIN,EXTERNAL`) into the
+    /// set of capabilities the server has advertised. Capability tokens this crate does not
+    /// recognize are silently ignored, exactly as unrequested capabilities are. Returns `true`
+    /// if the reply carried the trailing `*` continuation marker, meaning more `LS` lines are
+    /// still to come.
+    pub fn handle_ls_reply(&mut self, message: &Message) -> bool {
+        let more = message.args.get(2).map(|arg| &arg[..]) == Some("*");
+        if let Some(ref tokens) = message.suffix {
+            for token in tokens.split(' ').filter(|s| !s.is_empty()) {
+                let mut parts = token.splitn(2, '=');
+                let name = parts.next().unwrap_or("");
+                let value = parts.next().map(|s| s.to_owned());
+                if let Ok(capability) = name.parse() {
+                    self.available.push((capability, value));
+                }
+            }
+        }
+        self.more_to_come = more;
+        more
+    }
+
+    /// Whether the server has indicated (via a trailing `*`) that more `CAP * LS` lines are
+    /// still coming.
+    pub fn more_to_come(&self) -> bool {
+        self.more_to_come
+    }
+
+    /// The capabilities that were both requested and advertised by the server, along with
+    /// whatever value the server attached to each.
+    pub fn intersection(&self) -> Vec<(Capability, Option<String>)> {
+        self.available.iter().filter(|cap| self.requested.contains(&cap.0)).cloned().collect()
+    }
+
+    /// Builds the `CAP REQ` message asking the server to enable the intersection of the
+    /// requested and advertised capabilities. Returns `None` if that intersection is empty,
+    /// since there would be nothing to request.
+    pub fn request(&self) -> Option<Message> {
+        let wanted = self.intersection();
+        if wanted.is_empty() { return None }
+        let names: Vec<_> = wanted.iter().map(|cap| cap.0.as_ref()).collect();
+        Some(Message::new(None, "CAP", Some(vec!["REQ"]), Some(&names.join(" "))))
+    }
+
+    /// Builds the `CAP END` message that finishes negotiation.
+    pub fn end() -> Message {
+        Message::new(None, "CAP", Some(vec!["END"]), None)
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use super::{Capability, NegotiationState};
     use super::Capability::*;
+    use client::data::message::Message;
 
     #[test]
     fn to_str() {
@@ -35,5 +170,59 @@ mod test {
         assert_eq!(AccountNotify.as_ref(), "account-notify");
         assert_eq!(AwayNotify.as_ref(), "away-notify");
         assert_eq!(ExtendedJoin.as_ref(), "extended-join");
+        assert_eq!(ServerTime.as_ref(), "server-time");
+        assert_eq!(Sasl.as_ref(), "sasl");
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("multi-prefix".parse(), Ok(MultiPrefix));
+        assert_eq!("sasl".parse(), Ok(Sasl));
+        assert_eq!("userhost-in-names".parse(), Ok(UserhostInNames));
+        assert_eq!("made-up-capability".parse::<Capability>(),
+                   Err(super::UnknownCapability(format!("made-up-capability"))));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn negotiation_intersection_and_request() {
+        let mut state = NegotiationState::new(vec![ServerTime, Sasl, MultiPrefix]);
+        let first = Message::new(
+            None, "CAP", Some(vec!["*", "LS", "*"]), Some("multi-prefix sasl=PLAIN,EXTERNAL"),
+        );
+        assert!(state.handle_ls_reply(&first));
+        assert!(state.more_to_come());
+        let second = Message::new(
+            None, "CAP", Some(vec!["*", "LS"]), Some("account-notify server-time"),
+        );
+        assert!(!state.handle_ls_reply(&second));
+        assert!(!state.more_to_come());
+
+        let mut intersection = state.intersection();
+        intersection.sort_by(|a, b| a.0.as_ref().cmp(b.0.as_ref()));
+        assert_eq!(intersection, vec![
+            (MultiPrefix, None),
+            (Sasl, Some(format!("PLAIN,EXTERNAL"))),
+            (ServerTime, None),
+        ]);
+
+        let req = state.request().unwrap();
+        assert_eq!(req.command, "CAP");
+        assert_eq!(req.args, vec![format!("REQ")]);
+        assert!(req.suffix.is_some());
+    }
+
+    #[test]
+    fn negotiation_request_empty_when_no_overlap() {
+        let mut state = NegotiationState::new(vec![Batch]);
+        let reply = Message::new(None, "CAP", Some(vec!["*", "LS"]), Some("sasl"));
+        state.handle_ls_reply(&reply);
+        assert_eq!(state.request(), None);
+    }
+
+    #[test]
+    fn end_and_request_ls() {
+        assert_eq!(NegotiationState::end(), Message::new(None, "CAP", Some(vec!["END"]), None));
+        assert_eq!(NegotiationState::request_ls(),
+                   Message::new(None, "CAP", Some(vec!["LS", "302"]), None));
+    }
+}
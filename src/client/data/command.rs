@@ -0,0 +1,293 @@
+//! A typed view over the most common [RFC 2812](http://tools.ietf.org/html/rfc2812) commands and
+//! numeric replies, built on top of the untyped `Message`.
+use client::data::message::Message;
+
+/// A typed IRC command, split into the fields that are semantically meaningful for that command
+/// rather than left as a raw `args`/`suffix` pair. Use `Command::from_message` to classify an
+/// incoming `Message`, and match exhaustively on the result instead of comparing
+/// `message.command` against string literals. Commands this crate does not know a typed shape
+/// for fall back to `Numeric` (for three-digit server replies) or `Raw` (for everything else),
+/// so no traffic is lost.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    /// `PRIVMSG <target> :<text>`
+    PrivMsg {
+        /// The nickname or channel the message is addressed to.
+        target: String,
+        /// The message text.
+        text: String,
+    },
+    /// `NOTICE <target> :<text>`
+    Notice {
+        /// The nickname or channel the notice is addressed to.
+        target: String,
+        /// The notice text.
+        text: String,
+    },
+    /// `JOIN <channels> [<keys>]`
+    Join {
+        /// A comma-separated list of channels to join.
+        channels: String,
+        /// A comma-separated list of keys, one per channel that requires one.
+        keys: Option<String>,
+    },
+    /// `PART <channels> [:<message>]`
+    Part {
+        /// A comma-separated list of channels to leave.
+        channels: String,
+        /// An optional parting message.
+        message: Option<String>,
+    },
+    /// `NICK <nickname>`
+    Nick {
+        /// The requested nickname.
+        nickname: String,
+    },
+    /// `MODE <target> <modes> [<params>...]`
+    Mode {
+        /// The channel or nickname the modes apply to.
+        target: String,
+        /// The mode string, e.g. `+o` or `-b`.
+        modes: String,
+        /// Any parameters the mode string requires, e.g. a nickname for `+o`.
+        params: Vec<String>,
+    },
+    /// `TOPIC <channel> [:<topic>]`
+    Topic {
+        /// The channel whose topic is being read or set.
+        channel: String,
+        /// The new topic, or `None` when this is a request to read the current one.
+        topic: Option<String>,
+    },
+    /// `KICK <channel> <user> [:<comment>]`
+    Kick {
+        /// The channel the user is being kicked from.
+        channel: String,
+        /// The nickname of the user being kicked.
+        user: String,
+        /// An optional reason for the kick.
+        comment: Option<String>,
+    },
+    /// `PING <server> [<server2>]`
+    Ping {
+        /// The server that should respond with `PONG`.
+        server: String,
+        /// An optional second server, as used when forwarding between servers.
+        server2: Option<String>,
+    },
+    /// `PONG <server> [<server2>]`
+    Pong {
+        /// The server responding to a `PING`.
+        server: String,
+        /// An optional second server, as used when forwarding between servers.
+        server2: Option<String>,
+    },
+    /// `QUIT [:<message>]`
+    Quit {
+        /// An optional quit message.
+        message: Option<String>,
+    },
+    /// `CAP <subcommand> [<params>...]`
+    Cap {
+        /// The first argument of the message: the subcommand (e.g. `LS`, `REQ`, or `END`) in a
+        /// client's message. A server's reply puts its target (a nickname or `*`) first, so there
+        /// this holds the target and the subcommand is the first element of `params`.
+        subcommand: String,
+        /// The remaining arguments, with the suffix, if any, as the last element.
+        params: Vec<String>,
+    },
+    /// A three-digit numeric reply, along with its parameters (the target is always the first).
+    /// Note that `into_message` re-emits the last parameter as a trailing arg rather than a
+    /// `:`-suffix, so the result is not byte-identical to the original `Message`, and a last
+    /// parameter that contains a space is split into several args once serialized and re-parsed.
+    Numeric(u16, Vec<String>),
+    /// Any command this type does not give a typed shape to, kept as the raw command and
+    /// arguments (with the suffix, if any, as the last element). As with `Numeric`,
+    /// `into_message` re-emits that last element as a trailing arg rather than a `:`-suffix, so
+    /// a last element containing a space is split into several args once serialized and re-parsed.
+    Raw(String, Vec<String>),
+}
+
+impl Command {
+    /// Classifies a `Message` into a typed `Command`, splitting its `args`/`suffix` into the
+    /// fields that are meaningful for that command. Unrecognized commands become `Raw`, and
+    /// three-digit commands become `Numeric`, so every `Message` can be classified.
+    pub fn from_message(message: &Message) -> Command {
+        let args = &message.args;
+        let suffix = message.suffix.as_ref().map(|s| &s[..]);
+        match &message.command[..] {
+            // The text is normally the suffix (`PRIVMSG #c :hi there`), but some senders omit
+            // the `:` and split the text across several trailing args instead (`PRIVMSG #c hi
+            // there`); fall back to rejoining those so the text isn't silently truncated.
+            "PRIVMSG" if args.len() >= 1 => Command::PrivMsg {
+                target: args[0].clone(),
+                text: suffix.map(|s| s.to_owned()).unwrap_or_else(|| args[1..].join(" ")),
+            },
+            "NOTICE" if args.len() >= 1 => Command::Notice {
+                target: args[0].clone(),
+                text: suffix.map(|s| s.to_owned()).unwrap_or_else(|| args[1..].join(" ")),
+            },
+            "JOIN" if args.len() >= 1 => Command::Join {
+                channels: args[0].clone(),
+                keys: args.get(1).cloned(),
+            },
+            "PART" if args.len() >= 1 => Command::Part {
+                channels: args[0].clone(),
+                message: suffix.map(|s| s.to_owned()),
+            },
+            "NICK" if args.len() >= 1 => Command::Nick { nickname: args[0].clone() },
+            "MODE" if args.len() >= 2 => Command::Mode {
+                target: args[0].clone(),
+                modes: args[1].clone(),
+                params: args[2..].to_vec(),
+            },
+            "TOPIC" if args.len() >= 1 => Command::Topic {
+                channel: args[0].clone(),
+                topic: suffix.map(|s| s.to_owned()),
+            },
+            "KICK" if args.len() >= 2 => Command::Kick {
+                channel: args[0].clone(),
+                user: args[1].clone(),
+                comment: suffix.map(|s| s.to_owned()),
+            },
+            "PING" if args.len() >= 1 || suffix.is_some() => Command::Ping {
+                server: args.get(0).cloned().or(suffix.map(|s| s.to_owned())).unwrap_or_default(),
+                server2: args.get(1).cloned(),
+            },
+            "PONG" if args.len() >= 1 || suffix.is_some() => Command::Pong {
+                server: args.get(0).cloned().or(suffix.map(|s| s.to_owned())).unwrap_or_default(),
+                server2: args.get(1).cloned(),
+            },
+            "QUIT" => Command::Quit { message: suffix.map(|s| s.to_owned()) },
+            "CAP" if args.len() >= 1 => Command::Cap {
+                subcommand: args[0].clone(),
+                params: args[1..].iter().cloned()
+                    .chain(suffix.map(|s| s.to_owned())).collect(),
+            },
+            command if command.len() == 3 && command.chars().all(|c| c.is_ascii_digit()) => {
+                let mut params = args.clone();
+                params.extend(suffix.map(|s| s.to_owned()));
+                Command::Numeric(command.parse().unwrap(), params)
+            }
+            command => {
+                let mut params = args.clone();
+                params.extend(suffix.map(|s| s.to_owned()));
+                Command::Raw(command.to_owned(), params)
+            }
+        }
+    }
+
+    /// Builds the `Message` this `Command` represents.
+    pub fn into_message(self) -> Message {
+        match self {
+            Command::PrivMsg { target, text } =>
+                Message::new(None, "PRIVMSG", Some(vec![&target]), Some(&text)),
+            Command::Notice { target, text } =>
+                Message::new(None, "NOTICE", Some(vec![&target]), Some(&text)),
+            Command::Join { channels, keys } => Message::new(
+                None, "JOIN",
+                Some(keys.iter().map(|s| &s[..]).fold(vec![&channels[..]], |mut v, k| { v.push(k); v })),
+                None,
+            ),
+            Command::Part { channels, message } =>
+                Message::new(None, "PART", Some(vec![&channels]), message.as_ref().map(|s| &s[..])),
+            Command::Nick { nickname } => Message::new(None, "NICK", Some(vec![&nickname]), None),
+            Command::Mode { target, modes, params } => {
+                let mut args = vec![&target[..], &modes[..]];
+                args.extend(params.iter().map(|s| &s[..]));
+                Message::new(None, "MODE", Some(args), None)
+            }
+            Command::Topic { channel, topic } =>
+                Message::new(None, "TOPIC", Some(vec![&channel]), topic.as_ref().map(|s| &s[..])),
+            Command::Kick { channel, user, comment } => Message::new(
+                None, "KICK", Some(vec![&channel, &user]), comment.as_ref().map(|s| &s[..]),
+            ),
+            Command::Ping { server, server2 } => Message::new(
+                None, "PING",
+                server2.as_ref().map(|s| vec![&server[..], &s[..]]), if server2.is_none() {
+                    Some(&server[..])
+                } else {
+                    None
+                },
+            ),
+            Command::Pong { server, server2 } => Message::new(
+                None, "PONG",
+                server2.as_ref().map(|s| vec![&server[..], &s[..]]), if server2.is_none() {
+                    Some(&server[..])
+                } else {
+                    None
+                },
+            ),
+            Command::Quit { message } => Message::new(None, "QUIT", None, message.as_ref().map(|s| &s[..])),
+            Command::Cap { subcommand, params } => {
+                let params: Vec<_> = params.iter().map(|s| &s[..]).collect();
+                let mut args = vec![&subcommand[..]];
+                args.extend(params);
+                Message::new(None, "CAP", Some(args), None)
+            }
+            Command::Numeric(n, params) => {
+                let args: Vec<_> = params.iter().map(|s| &s[..]).collect();
+                Message::new(None, &format!("{:03}", n), if args.is_empty() { None } else { Some(args) }, None)
+            }
+            Command::Raw(command, params) => {
+                let args: Vec<_> = params.iter().map(|s| &s[..]).collect();
+                Message::new(None, &command, if args.is_empty() { None } else { Some(args) }, None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Command;
+    use client::data::message::Message;
+
+    #[test]
+    fn from_message_privmsg() {
+        let message = Message::new(None, "PRIVMSG", Some(vec!["#chan"]), Some("hi there"));
+        assert_eq!(Command::from_message(&message), Command::PrivMsg {
+            target: format!("#chan"), text: format!("hi there"),
+        });
+    }
+
+    #[test]
+    fn from_message_privmsg_no_suffix() {
+        // Some senders omit the `:` entirely, splitting the text across several trailing args;
+        // none of it should be silently dropped.
+        let message = Message::new(None, "PRIVMSG", Some(vec!["#chan", "hi"]), None);
+        assert_eq!(Command::from_message(&message), Command::PrivMsg {
+            target: format!("#chan"), text: format!("hi"),
+        });
+        let message = Message::new(None, "PRIVMSG", Some(vec!["#chan", "hello", "world"]), None);
+        assert_eq!(Command::from_message(&message), Command::PrivMsg {
+            target: format!("#chan"), text: format!("hello world"),
+        });
+    }
+
+    #[test]
+    fn from_message_numeric() {
+        let message = Message::new(Some("irc.test.net"), "001", Some(vec!["nick"]),
+                                    Some("Welcome"));
+        assert_eq!(Command::from_message(&message),
+                   Command::Numeric(1, vec![format!("nick"), format!("Welcome")]));
+    }
+
+    #[test]
+    fn from_message_raw() {
+        let message = Message::new(None, "AWAY", None, Some("gone fishing"));
+        assert_eq!(Command::from_message(&message), Command::Raw(
+            format!("AWAY"), vec![format!("gone fishing")],
+        ));
+    }
+
+    #[test]
+    fn into_message_round_trip() {
+        let command = Command::Kick {
+            channel: format!("#chan"), user: format!("baduser"), comment: Some(format!("bye")),
+        };
+        let message = command.into_message();
+        assert_eq!(message, Message::new(
+            None, "KICK", Some(vec!["#chan", "baduser"]), Some("bye"),
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+//! [CTCP](http://www.irchelp.org/protocol/ctcpspec.html) messages layered on top of `Message`.
+use client::data::message::Message;
+
+/// The byte that delimits a CTCP payload inside a `PRIVMSG`/`NOTICE` suffix.
+const CTCP_DELIM: char = '\u{0001}';
+
+/// The low-level CTCP quote character, used to protect bytes that would otherwise corrupt the
+/// IRC line (or be mistaken for the CTCP delimiter) as they cross the wire.
+const CTCP_QUOTE: char = '\u{0010}';
+
+/// A single CTCP request or response, e.g. `ACTION waves`, `VERSION`, or `PING 1234567890`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ctcp {
+    /// The CTCP command, e.g. `"ACTION"` or `"VERSION"`.
+    pub command: String,
+    /// The parameters following the command, if any.
+    pub params: Option<String>,
+}
+
+impl Ctcp {
+    /// Creates a new CTCP message.
+    pub fn new(command: &str, params: Option<&str>) -> Ctcp {
+        Ctcp { command: command.to_owned(), params: params.map(|s| s.to_owned()) }
+    }
+
+    /// Wraps this CTCP message into a `PRIVMSG` or `NOTICE` `Message` addressed to `target`,
+    /// delimiting and low-level quoting the payload as the CTCP spec requires.
+    pub fn into_message(self, irc_command: &str, target: &str) -> Message {
+        let mut payload = String::new();
+        payload.push(CTCP_DELIM);
+        payload.push_str(&low_level_quote(&self.command));
+        if let Some(ref params) = self.params {
+            payload.push(' ');
+            payload.push_str(&low_level_quote(params));
+        }
+        payload.push(CTCP_DELIM);
+        Message::new(None, irc_command, Some(vec![target]), Some(&payload))
+    }
+}
+
+impl Message {
+    /// If this is a `PRIVMSG` or `NOTICE` whose suffix is a CTCP-delimited payload, parses and
+    /// low-level dequotes it into a `Ctcp`. Returns `None` for any other message, including a
+    /// plain-text `PRIVMSG`/`NOTICE` whose suffix doesn't start and end with the CTCP delimiter.
+    pub fn as_ctcp(&self) -> Option<Ctcp> {
+        if self.command != "PRIVMSG" && self.command != "NOTICE" { return None }
+        let suffix = match self.suffix {
+            Some(ref suffix) => suffix,
+            None => return None,
+        };
+        if suffix.len() < 2 || !suffix.starts_with(CTCP_DELIM) || !suffix.ends_with(CTCP_DELIM) {
+            return None
+        }
+        let inner = low_level_dequote(&suffix[CTCP_DELIM.len_utf8()..suffix.len() - CTCP_DELIM.len_utf8()]);
+        let mut parts = inner.splitn(2, ' ');
+        let command = parts.next().unwrap_or("").to_owned();
+        let params = parts.next().map(|s| s.to_owned());
+        Some(Ctcp { command: command, params: params })
+    }
+}
+
+/// Escapes NUL, CR, LF, the CTCP delimiter, and the quote character itself with a `CTCP_QUOTE`
+/// prefix, so that none of them can be mistaken for message framing as they cross the wire.
+fn low_level_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\0' => { quoted.push(CTCP_QUOTE); quoted.push('0') }
+            '\n' => { quoted.push(CTCP_QUOTE); quoted.push('n') }
+            '\r' => { quoted.push(CTCP_QUOTE); quoted.push('r') }
+            CTCP_DELIM => { quoted.push(CTCP_QUOTE); quoted.push('a') }
+            CTCP_QUOTE => { quoted.push(CTCP_QUOTE); quoted.push(CTCP_QUOTE) }
+            c => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+/// The inverse of `low_level_quote`. An unrecognized quoted character drops the quote and keeps
+/// the character that follows it, and a trailing lone quote character is dropped entirely.
+fn low_level_dequote(s: &str) -> String {
+    let mut dequoted = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != CTCP_QUOTE {
+            dequoted.push(c);
+            continue
+        }
+        match chars.next() {
+            Some('0') => dequoted.push('\0'),
+            Some('n') => dequoted.push('\n'),
+            Some('r') => dequoted.push('\r'),
+            Some('a') => dequoted.push(CTCP_DELIM),
+            Some(CTCP_QUOTE) => dequoted.push(CTCP_QUOTE),
+            Some(c) => dequoted.push(c),
+            None => (),
+        }
+    }
+    dequoted
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ctcp;
+    use client::data::message::Message;
+
+    #[test]
+    fn as_ctcp_action() {
+        let message = Message::new(
+            None, "PRIVMSG", Some(vec!["#chan"]), Some("\u{0001}ACTION waves\u{0001}"),
+        );
+        assert_eq!(message.as_ctcp(), Some(Ctcp::new("ACTION", Some("waves"))));
+    }
+
+    #[test]
+    fn as_ctcp_no_params() {
+        let message = Message::new(None, "PRIVMSG", Some(vec!["#chan"]), Some("\u{0001}VERSION\u{0001}"));
+        assert_eq!(message.as_ctcp(), Some(Ctcp::new("VERSION", None)));
+    }
+
+    #[test]
+    fn as_ctcp_rejects_plain_text() {
+        let message = Message::new(None, "PRIVMSG", Some(vec!["#chan"]), Some("just chatting"));
+        assert_eq!(message.as_ctcp(), None);
+        let message = Message::new(None, "NOTICE", Some(vec!["#chan"]), None);
+        assert_eq!(message.as_ctcp(), None);
+    }
+
+    #[test]
+    fn into_message_round_trip() {
+        let ctcp = Ctcp::new("ACTION", Some("waves"));
+        let message = ctcp.clone().into_message("PRIVMSG", "#chan");
+        assert_eq!(message.as_ctcp(), Some(ctcp));
+    }
+
+    #[test]
+    fn round_trip_literal_delimiter_in_params() {
+        let ctcp = Ctcp::new("ACTION", Some("contains a \u{0001} byte"));
+        let message = ctcp.clone().into_message("PRIVMSG", "#chan");
+        assert_eq!(message.as_ctcp(), Some(ctcp));
+    }
+}
@@ -1,6 +1,7 @@
 //! Messages to and from the server.
 use std::borrow::ToOwned;
-use std::str::FromStr;
+use std::fmt;
+use std::str::{self, FromStr};
 
 /// IRC Message data.
 #[derive(Clone, PartialEq, Debug)]
@@ -57,9 +58,29 @@ impl Message {
         )
     }
 
+    /// Gets the value of a tag with the given key, if the message has a tag with that key.
+    pub fn get_tag(&self, key: &str) -> Option<&str> {
+        self.tags.as_ref().and_then(|tags| tags.iter().find(|tag| &tag.0[..] == key))
+                  .and_then(|tag| tag.1.as_ref().map(|v| &v[..]))
+    }
+
     /// Converts a Message into a String according to the IRC protocol.
     pub fn into_string(&self) -> String {
         let mut ret = String::new();
+        if let Some(ref tags) = self.tags {
+            if !tags.is_empty() {
+                ret.push('@');
+                for (i, tag) in tags.iter().enumerate() {
+                    if i != 0 { ret.push(';') }
+                    ret.push_str(&tag.0);
+                    if let Some(ref value) = tag.1 {
+                        ret.push('=');
+                        ret.push_str(&escape_tag_value(value));
+                    }
+                }
+                ret.push(' ');
+            }
+        }
         if let Some(ref prefix) = self.prefix {
             ret.push(':');
             ret.push_str(&prefix);
@@ -77,46 +98,77 @@ impl Message {
         ret.push_str("\r\n");
         ret
     }
-}
 
-impl FromStr for Message {
-    type Err = &'static str;
-    fn from_str(s: &str) -> Result<Message, &'static str> {
-        let mut state = s.clone();
-        if s.len() == 0 { return Err("Cannot parse an empty string as a message.") }
+    /// Parses the leading `\r\n`-terminated message out of `input`, returning it along with the
+    /// unconsumed remainder of the buffer. This lets a socket reader keep feeding in a growing
+    /// buffer and pull out complete messages one at a time, without re-scanning bytes it has
+    /// already consumed. Returns `Err(ParseError::Incomplete)` when `input` does not yet contain
+    /// a full message, which callers should treat as "read more and try again" rather than as a
+    /// malformed message.
+    pub fn parse_bytes(input: &[u8]) -> Result<(&[u8], Message), ParseError> {
+        let line_end = match input.windows(2).position(|w| w == b"\r\n") {
+            Some(pos) => pos,
+            None => return Err(ParseError::Incomplete),
+        };
+        let (line, rest) = (&input[..line_end], &input[line_end + 2..]);
+        let line = match str::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) => return Err(ParseError::InvalidEncoding),
+        };
+        let message = Message::parse_line(line)?;
+        Ok((rest, message))
+    }
+
+    /// Parses a single message out of `line`, which must not contain the terminating `\r\n`.
+    fn parse_line(line: &str) -> Result<Message, ParseError> {
+        let mut state = line;
+        if state.len() == 0 { return Err(ParseError::EmptyMessage) }
         let tags = if state.starts_with("@") {
             let tags = state.find(' ').map(|i| &state[1..i]);
-            state = state.find(' ').map_or("", |i| &state[i+1..]);
+            state = match state.find(' ') {
+                Some(i) => &state[i+1..],
+                None => return Err(ParseError::TruncatedTag),
+            };
             tags.map(|ts| ts.split(";").filter(|s| s.len() != 0).map(|s: &str| {
                 let mut iter = s.splitn(2, "=");
                 let (fst, snd) = (iter.next(), iter.next());
-                Tag(fst.unwrap_or("").to_owned(), snd.map(|s| s.to_owned()))
+                Tag(fst.unwrap_or("").to_owned(), snd.map(|s| unescape_tag_value(s)))
             }).collect::<Vec<_>>())
         } else {
             None
         };
         let prefix = if state.starts_with(":") {
             let prefix = state.find(' ').map(|i| &state[1..i]);
-            state = state.find(' ').map_or("", |i| &state[i+1..]);
+            state = match state.find(' ') {
+                Some(i) => &state[i+1..],
+                None => return Err(ParseError::InvalidPrefix),
+            };
             prefix
         } else {
             None
         };
         let suffix = if state.contains(" :") {
-            let suffix = state.find(" :").map(|i| &state[i+2..state.len()-2]);
-            state = state.find(" :").map_or("", |i| &state[..i+1]);
+            let suffix = state.find(" :").map(|i| &state[i+2..]);
+            state = state.find(" :").map_or("", |i| &state[..i]);
             suffix
         } else {
             None
         };
-        let command = match state.find(' ').map(|i| &state[..i]) {
-            Some(cmd) => {
-                state = state.find(' ').map_or("", |i| &state[i+1..]);
+        let command = match state.find(' ') {
+            Some(i) => {
+                let cmd = &state[..i];
+                state = &state[i+1..];
+                cmd
+            }
+            // No space left in `state`: it's either a bare command with no args (fine, as long
+            // as there's something there) or, if `state` is empty, there was no command at all.
+            None if state.len() != 0 => {
+                let cmd = state;
+                state = "";
                 cmd
             }
-            _ => return Err("Cannot parse a message without a command.")
+            None => return Err(ParseError::MissingCommand),
         };
-        if suffix.is_none() { state = &state[..state.len() - 2] }
         let args: Vec<_> = state.splitn(14, ' ').filter(|s| s.len() != 0).collect();
         Ok(Message::with_tags(
             tags, prefix, command, if args.len() > 0 { Some(args) } else { None }, suffix
@@ -124,6 +176,43 @@ impl FromStr for Message {
     }
 }
 
+/// An error encountered while parsing a raw IRC message.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseError {
+    /// The buffer did not yet contain a full `\r\n`-terminated message; more bytes are needed.
+    Incomplete,
+    /// The line was not valid UTF-8.
+    InvalidEncoding,
+    /// The input was empty.
+    EmptyMessage,
+    /// The message was missing its command.
+    MissingCommand,
+    /// The IRCv3 message tags were not terminated by a space before the rest of the message.
+    TruncatedTag,
+    /// The message prefix was not terminated by a space before the rest of the message.
+    InvalidPrefix,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ParseError::Incomplete => "message buffer does not yet contain a complete message",
+            ParseError::InvalidEncoding => "message line was not valid UTF-8",
+            ParseError::EmptyMessage => "cannot parse an empty string as a message",
+            ParseError::MissingCommand => "cannot parse a message without a command",
+            ParseError::TruncatedTag => "message tags were not terminated by a space",
+            ParseError::InvalidPrefix => "message prefix was not terminated by a space",
+        })
+    }
+}
+
+impl FromStr for Message {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Message, ParseError> {
+        Message::parse_bytes(s.as_bytes()).map(|(_, message)| message)
+    }
+}
+
 impl<'a> From<&'a str> for Message {
     fn from(s: &'a str) -> Message {
         s.parse().unwrap()
@@ -131,12 +220,62 @@ impl<'a> From<&'a str> for Message {
 }
 
 /// A message tag as defined by [IRCv3.2](http://ircv3.net/specs/core/message-tags-3.2.html).
+/// The value is stored unescaped; escaping only happens on the wire.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Tag(String, Option<String>);
 
+impl Tag {
+    /// Creates a new tag from an already-unescaped key and value.
+    pub(crate) fn new(key: String, value: Option<String>) -> Tag {
+        Tag(key, value)
+    }
+}
+
+/// Removes the IRCv3 message-tag escape sequences from a raw tag value, turning `\:` into `;`,
+/// `\s` into a space, `\\` into `\`, `\r` into a carriage return, and `\n` into a line feed. An
+/// unrecognized escape drops the backslash and keeps the following character, and a trailing
+/// lone backslash is dropped entirely.
+pub(crate) fn unescape_tag_value(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('\\') => unescaped.push('\\'),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => (),
+        }
+    }
+    unescaped
+}
+
+/// Applies the IRCv3 message-tag escape sequences to a raw tag value, turning `;` into `\:`, a
+/// space into `\s`, `\` into `\\`, a carriage return into `\r`, and a line feed into `\n`.
+fn escape_tag_value(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Message, Tag};
+    use super::{Message, ParseError, Tag};
 
     #[test]
     fn new() {
@@ -260,4 +399,69 @@ mod test {
     fn to_message_invalid_format() {
         let _: Message = ":invalid :message".into();
     }
+
+    #[test]
+    fn tag_escaping_round_trip() {
+        let message = Message {
+            tags: Some(vec![Tag(format!("client-id"), Some(format!("foo bar;baz\\qux")))]),
+            prefix: None,
+            command: format!("PRIVMSG"),
+            args: vec![format!("test")],
+            suffix: Some(format!("Testing!")),
+        };
+        let raw = message.into_string();
+        assert_eq!(&raw[..], "@client-id=foo\\sbar\\:baz\\\\qux PRIVMSG test :Testing!\r\n");
+        assert_eq!(raw.parse(), Ok(message));
+    }
+
+    #[test]
+    fn get_tag() {
+        let message: Message = "@aaa=bbb;ccc;example.com/ddd=eee :test!test@test PRIVMSG test \
+                                 :Testing with tags!\r\n".into();
+        assert_eq!(message.get_tag("aaa"), Some("bbb"));
+        assert_eq!(message.get_tag("ccc"), None);
+        assert_eq!(message.get_tag("example.com/ddd"), Some("eee"));
+        assert_eq!(message.get_tag("missing"), None);
+    }
+
+    #[test]
+    fn from_string_suffix_only_no_args() {
+        let message = Message {
+            tags: None,
+            prefix: None,
+            command: format!("PING"),
+            args: vec![],
+            suffix: Some(format!("irc.example.net")),
+        };
+        assert_eq!("PING :irc.example.net\r\n".parse(), Ok(message));
+        let message = Message {
+            tags: None,
+            prefix: None,
+            command: format!("QUIT"),
+            args: vec![],
+            suffix: Some(format!("bye")),
+        };
+        assert_eq!("QUIT :bye\r\n".parse(), Ok(message));
+        let msg: Message = "PING :irc.example.net\r\n".into();
+        assert_eq!(msg.command, "PING");
+        assert_eq!(msg.suffix, Some(format!("irc.example.net")));
+    }
+
+    #[test]
+    fn parse_bytes_incremental() {
+        let buf = b"PRIVMSG #chan :hello1\r\nPRIVMSG #chan :hello2\r\nNOT DONE YET";
+        let (rest, first) = Message::parse_bytes(buf).unwrap();
+        assert_eq!(first, Message::new(None, "PRIVMSG", Some(vec!["#chan"]), Some("hello1")));
+        let (rest, second) = Message::parse_bytes(rest).unwrap();
+        assert_eq!(second, Message::new(None, "PRIVMSG", Some(vec!["#chan"]), Some("hello2")));
+        assert_eq!(Message::parse_bytes(rest), Err(ParseError::Incomplete));
+    }
+
+    #[test]
+    fn parse_bytes_errors() {
+        assert_eq!(Message::parse_bytes(b"PRIVMSG #chan :hello"), Err(ParseError::Incomplete));
+        assert_eq!(Message::parse_bytes(b"\r\n"), Err(ParseError::EmptyMessage));
+        assert_eq!(Message::parse_bytes(b"@unterminated\r\n"), Err(ParseError::TruncatedTag));
+        assert_eq!(Message::parse_bytes(b":unterminated\r\n"), Err(ParseError::InvalidPrefix));
+    }
 }
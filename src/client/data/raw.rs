@@ -0,0 +1,201 @@
+//! A zero-copy, range-based view over a raw IRC protocol line.
+use std::ops::Range;
+
+use client::data::message::{Message, ParseError, Tag, unescape_tag_value};
+
+/// A borrowed view over a single `\r\n`-terminated IRC line. Unlike [`Message`](struct.Message.html),
+/// which heap-allocates a `String` for the command, every argument, and the prefix and suffix,
+/// `RawMessage` keeps the original line and records byte ranges into it, so parsing a line costs
+/// no allocations beyond the small vector of tag/argument ranges. Call `into_owned` to copy the
+/// parts out into a `Message` once the caller needs to keep them past the line buffer's lifetime.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RawMessage<'a> {
+    source: &'a str,
+    tags: Vec<(Range<usize>, Option<Range<usize>>)>,
+    prefix: Option<Range<usize>>,
+    command: Range<usize>,
+    args: Vec<Range<usize>>,
+    suffix: Option<Range<usize>>,
+}
+
+impl<'a> RawMessage<'a> {
+    /// Parses `line`, which must not contain the terminating `\r\n`, into a borrowed view over
+    /// its bytes. This mirrors `Message::parse_line`'s grammar exactly, but records ranges into
+    /// `line` instead of copying each part out into its own `String`.
+    pub fn parse(line: &'a str) -> Result<RawMessage<'a>, ParseError> {
+        if line.is_empty() { return Err(ParseError::EmptyMessage) }
+
+        let mut pos = 0;
+        let mut tags = Vec::new();
+        if line.as_bytes()[0] == b'@' {
+            let start = 1;
+            let space = match line[start..].find(' ') {
+                Some(i) => start + i,
+                None => return Err(ParseError::TruncatedTag),
+            };
+            let mut cursor = start;
+            for part in line[start..space].split(';') {
+                let part_start = cursor;
+                let part_end = part_start + part.len();
+                cursor = part_end + 1;
+                if part.is_empty() { continue }
+                match part.find('=') {
+                    Some(eq) =>
+                        tags.push((part_start..part_start + eq, Some(part_start + eq + 1..part_end))),
+                    None => tags.push((part_start..part_end, None)),
+                }
+            }
+            pos = space + 1;
+        }
+
+        let prefix = if line[pos..].starts_with(':') {
+            let start = pos + 1;
+            let space = match line[start..].find(' ') {
+                Some(i) => start + i,
+                None => return Err(ParseError::InvalidPrefix),
+            };
+            pos = space + 1;
+            Some(start..space)
+        } else {
+            None
+        };
+
+        let (body_end, suffix) = match line[pos..].find(" :") {
+            Some(i) => (pos + i, Some(pos + i + 2..line.len())),
+            None => (line.len(), None),
+        };
+        let body = &line[pos..body_end];
+        let command_end = match body.find(' ') {
+            Some(i) => pos + i,
+            // No space left in `body`: it's either a bare command with no args (fine, as long
+            // as there's something there) or, if `body` is empty, there was no command at all.
+            None if !body.is_empty() => body_end,
+            None => return Err(ParseError::MissingCommand),
+        };
+        let command = pos..command_end;
+
+        let mut args = Vec::new();
+        if command_end < body_end {
+            let mut cursor = command_end + 1;
+            for part in line[cursor..body_end].splitn(14, ' ') {
+                let part_start = cursor;
+                let part_end = part_start + part.len();
+                cursor = part_end + 1;
+                if !part.is_empty() { args.push(part_start..part_end) }
+            }
+        }
+
+        Ok(RawMessage { source: line, tags: tags, prefix: prefix, command: command, args: args,
+                         suffix: suffix })
+    }
+
+    /// The IRC command, e.g. `"PRIVMSG"`.
+    pub fn command(&self) -> &'a str {
+        &self.source[self.command.clone()]
+    }
+
+    /// The message prefix (or source), if one was present.
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.prefix.clone().map(|r| &self.source[r])
+    }
+
+    /// The command arguments, in order.
+    pub fn args(&self) -> impl Iterator<Item = &'a str> {
+        let source = self.source;
+        self.args.clone().into_iter().map(move |r| &source[r])
+    }
+
+    /// The message suffix, if one was present.
+    pub fn suffix(&self) -> Option<&'a str> {
+        self.suffix.clone().map(|r| &self.source[r])
+    }
+
+    /// The message tags, as raw `(key, value)` pairs straight off the wire. Values are still
+    /// escaped as defined by IRCv3.2; use `get_tag` or `into_owned` to get an unescaped value.
+    pub fn tags(&self) -> impl Iterator<Item = (&'a str, Option<&'a str>)> {
+        let source = self.source;
+        self.tags.clone().into_iter().map(move |(k, v)| (&source[k], v.map(|r| &source[r])))
+    }
+
+    /// Gets the unescaped value of a tag with the given key, if the message has one.
+    pub fn get_tag(&self, key: &str) -> Option<String> {
+        self.tags().find(|&(k, _)| k == key).and_then(|(_, v)| v.map(unescape_tag_value))
+    }
+
+    /// Copies the borrowed parts of this message out into an owned `Message`, unescaping tag
+    /// values along the way, so it can outlive the buffer `self` borrows from.
+    pub fn into_owned(&self) -> Message {
+        let tags = if self.tags.is_empty() {
+            None
+        } else {
+            Some(self.tags().map(|(k, v)| {
+                Tag::new(k.to_owned(), v.map(unescape_tag_value))
+            }).collect())
+        };
+        let args: Vec<_> = self.args().collect();
+        Message::with_tags(
+            tags, self.prefix(), self.command(), if args.is_empty() { None } else { Some(args) },
+            self.suffix(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RawMessage;
+    use client::data::message::{Message, ParseError};
+
+    #[test]
+    fn parse_basic() {
+        let raw = RawMessage::parse(":test!test@test PRIVMSG test :Testing!").unwrap();
+        assert_eq!(raw.prefix(), Some("test!test@test"));
+        assert_eq!(raw.command(), "PRIVMSG");
+        assert_eq!(raw.args().collect::<Vec<_>>(), vec!["test"]);
+        assert_eq!(raw.suffix(), Some("Testing!"));
+    }
+
+    #[test]
+    fn parse_with_tags() {
+        let raw = RawMessage::parse("@aaa=bbb;ccc;example.com/ddd=eee :test!test@test PRIVMSG \
+                                      test :Testing with tags!").unwrap();
+        assert_eq!(raw.get_tag("aaa"), Some(format!("bbb")));
+        assert_eq!(raw.get_tag("ccc"), None);
+        assert_eq!(raw.get_tag("example.com/ddd"), Some(format!("eee")));
+    }
+
+    #[test]
+    fn parse_escaped_tag_value() {
+        let raw = RawMessage::parse("@client-id=foo\\sbar PRIVMSG test :Testing!").unwrap();
+        assert_eq!(raw.get_tag("client-id"), Some(format!("foo bar")));
+    }
+
+    #[test]
+    fn into_owned_matches_message_parse() {
+        let line = ":test!test@test COMMAND ARG:test :Testing!";
+        let raw = RawMessage::parse(line).unwrap().into_owned();
+        let owned: Message = format!("{}\r\n", line).parse().unwrap();
+        assert_eq!(raw, owned);
+    }
+
+    #[test]
+    fn parse_suffix_only_no_args() {
+        let raw = RawMessage::parse("PING :irc.example.net").unwrap();
+        assert_eq!(raw.command(), "PING");
+        assert_eq!(raw.args().collect::<Vec<_>>(), Vec::<&str>::new());
+        assert_eq!(raw.suffix(), Some("irc.example.net"));
+
+        let raw = RawMessage::parse("PING").unwrap();
+        assert_eq!(raw.command(), "PING");
+        assert_eq!(raw.args().collect::<Vec<_>>(), Vec::<&str>::new());
+        assert_eq!(raw.suffix(), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(RawMessage::parse(""), Err(ParseError::EmptyMessage));
+        assert_eq!(RawMessage::parse("@unterminated"), Err(ParseError::TruncatedTag));
+        assert_eq!(RawMessage::parse(":unterminated"), Err(ParseError::InvalidPrefix));
+        // A prefix with nothing following it leaves no command at all.
+        assert_eq!(RawMessage::parse(":prefix "), Err(ParseError::MissingCommand));
+    }
+}